@@ -3,16 +3,26 @@
 use codec::{Encode, Decode};
 use frame_support::{
 	decl_module, decl_storage, decl_event, decl_error, ensure, StorageValue, StorageDoubleMap,
-	traits::Randomness, RuntimeDebug, dispatch::{DispatchError},
+	traits::{Randomness, Currency, ReservableCurrency, ExistenceRequirement, Get},
+	RuntimeDebug, dispatch::{DispatchError}, weights::Weight,
 };
 use sp_io::hashing::blake2_128;
-use frame_system::ensure_signed;
+use frame_system::{ensure_signed, ensure_root};
 
+mod migrations;
+
+#[cfg(test)]
+mod mock;
 #[cfg(test)]
 mod tests;
 
+type BalanceOf<T> = <<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
+
 #[derive(Encode, Decode, Clone, RuntimeDebug, PartialEq, Eq)]
-pub struct Kitty(pub [u8; 16]);
+pub struct Kitty {
+	pub dna: [u8; 16],
+	pub gen: u64,
+}
 
 #[derive(Encode, Decode, Clone, Copy, RuntimeDebug, PartialEq, Eq)]
 pub enum KittyGender {
@@ -23,7 +33,7 @@ pub enum KittyGender {
 impl Kitty {
 	pub fn gender(&self) -> KittyGender {
 		// take the first byte of the DNA and check wether it's even or not
-		if self.0[0] % 2 == 0 {
+		if self.dna[0] % 2 == 0 {
 			KittyGender::Male
 		} else {
 			KittyGender::Female
@@ -31,8 +41,33 @@ impl Kitty {
 	}
 }
 
+/// Storage layout versions, used to gate the one-shot migration that added `Kitty::gen`
+#[derive(Encode, Decode, Clone, Copy, RuntimeDebug, PartialEq, Eq)]
+pub enum Releases {
+	V1,
+	V2,
+}
+
+impl Default for Releases {
+	fn default() -> Self {
+		Releases::V1
+	}
+}
+
+/// An outbound message describing a kitty state transition, queued for an off-chain
+/// relayer (e.g. another parachain or an off-chain worker) to pick up
+#[derive(Encode, Decode, Clone, RuntimeDebug, PartialEq, Eq)]
+pub enum KittyMessage<AccountId> {
+	Created { owner: AccountId, kitty_id: u32, dna: [u8; 16] },
+	Transfer { dest: AccountId, kitty_id: u32 },
+}
+
 pub trait Config: frame_system::Config {
 	type Event: From<Event<Self>> + Into<<Self as frame_system::Config>::Event>;
+	type Currency: ReservableCurrency<Self::AccountId>;
+	/// Upper bound on how many outbound messages may sit in `SentMessages` awaiting a
+	/// relayer, so the queue can't be grown without bound between drains
+	type MaxSentMessages: Get<u32>;
 }
 
 decl_storage! {
@@ -41,17 +76,50 @@ decl_storage! {
 		pub Kitties get(fn kitties): double_map hasher(blake2_128_concat) T::AccountId, hasher(blake2_128_concat) u32 => Option<Kitty>;
 		/// Stores the next kitty ID
 		pub NextKittyId get(fn next_kitty_id): u32;
+		/// Stores how many kitties each account owns
+		pub OwnedKittiesCount get(fn owned_kitties_count): map hasher(blake2_128_concat) T::AccountId => u32;
+		/// Maps (owner, kitty id) to its position in the owner's gap-free `0..OwnedKittiesCount` range
+		pub OwnedKittiesIndex get(fn owned_kitties_index): map hasher(blake2_128_concat) (T::AccountId, u32) => u32;
+		/// Maps (owner, position) back to the kitty id stored there
+		pub OwnedKittiesArray get(fn kitty_of_owner_by_index): map hasher(blake2_128_concat) (T::AccountId, u32) => u32;
+		/// Stores the listed price of a kitty, if its owner has put it up for sale
+		pub KittyPrices get(fn kitty_price): double_map hasher(blake2_128_concat) T::AccountId, hasher(blake2_128_concat) u32 => Option<BalanceOf<T>>;
+		/// Incremented on every kitty-generating extrinsic and folded into its randomness,
+		/// so multiple kitties created in the same block don't share a seed
+		pub Nonce get(fn nonce): u64;
+		/// Total number of kitties in existence
+		pub AllKittiesCount get(fn all_kitties_count): u64;
+		/// Maps a global gap-free position to the (owner, kitty id) stored there
+		pub AllKittiesArray get(fn kitty_by_index): map hasher(blake2_128_concat) u64 => (T::AccountId, u32);
+		/// Maps (owner, kitty id) back to its position in `AllKittiesArray`
+		pub AllKittiesIndex get(fn all_kitties_index): map hasher(blake2_128_concat) (T::AccountId, u32) => u64;
+		/// Stores the (parent1, parent2) kitty ids a bred kitty was produced from
+		pub KittyParents get(fn kitty_parents): double_map hasher(blake2_128_concat) T::AccountId, hasher(blake2_128_concat) u32 => Option<(u32, u32)>;
+		/// Tracks the storage layout version, so `on_runtime_upgrade` only migrates once
+		pub StorageVersion get(fn storage_version): Releases;
+		/// Outbound messages queued for an off-chain relayer
+		pub SentMessages get(fn sent_messages): Vec<KittyMessage<T::AccountId>>;
 	}
 }
 
 decl_event! {
 	pub enum Event<T> where
 		<T as frame_system::Config>::AccountId,
+		Balance = BalanceOf<T>,
 	{
 		/// A kitty is created. \[owner, kitty_id, kitty\]
 		KittyCreated(AccountId, u32, Kitty),
 		/// A new kitten is bred. \[owner, kitty_id, kitty\]
 		KittyBred(AccountId, u32, Kitty),
+		/// A kitty is transferred. \[from, to, kitty_id\]
+		KittyTransferred(AccountId, AccountId, u32),
+		/// A kitty is sold. \[from, to, kitty_id, price\]
+		KittySold(AccountId, AccountId, u32, Balance),
+		/// A kitty was removed from local storage and queued for an off-chain relayer.
+		/// \[owner, kitty_id, dest\]
+		KittyEgressQueued(AccountId, u32, AccountId),
+		/// The outbound message queue was drained by a relayer. \[messages_drained\]
+		KittyMessagesDrained(u32),
 	}
 }
 
@@ -60,6 +128,17 @@ decl_error! {
 		KittiesIdOverflow,
 		InvalidKittyId,
 		SameGender,
+		NotOwner,
+		OwnedKittiesCountOverflow,
+		OwnedKittiesCountUnderflow,
+		NotForSale,
+		PriceTooHigh,
+		KittyAlreadyExists,
+		AllKittiesCountOverflow,
+		AllKittiesCountUnderflow,
+		GenerationOverflow,
+		CannotTransferToSelf,
+		SentMessagesQueueFull,
 	}
 }
 
@@ -69,6 +148,10 @@ decl_module! {
 
 		fn deposit_event() = default;
 
+		fn on_runtime_upgrade() -> Weight {
+			migrations::migrate_to_v2::<T>()
+		}
+
 		/// Create a new kitty
 		#[weight = 1000]
 		pub fn create(origin) {
@@ -77,10 +160,18 @@ decl_module! {
 			let kitty_id = Self::get_next_kitty_id()?;
 
 			let dna = Self::random_value(&sender);
+			ensure!(!Self::dna_exists(&dna), Error::<T>::KittyAlreadyExists);
 
 			// Create and store kitty
-			let kitty = Kitty(dna);
+			let kitty = Kitty { dna, gen: 0 };
 			Kitties::<T>::insert(&sender, kitty_id, &kitty);
+			Self::insert_owned_kitty(&sender, kitty_id)?;
+			Self::insert_all_kitty(&sender, kitty_id)?;
+
+			Self::ensure_message_queue_capacity()?;
+			SentMessages::<T>::append(KittyMessage::Created {
+				owner: sender.clone(), kitty_id, dna: kitty.dna,
+			});
 
 			// Emit event
 			Self::deposit_event(RawEvent::KittyCreated(sender, kitty_id, kitty));
@@ -98,8 +189,8 @@ decl_module! {
 
 			let kitty_id = Self::get_next_kitty_id()?;
 
-			let kitty1_dna = kitty1.0;
-			let kitty2_dna = kitty2.0;
+			let kitty1_dna = kitty1.dna;
+			let kitty2_dna = kitty2.dna;
 
 			let selector = Self::random_value(&sender);
 			let mut new_dna = [0u8; 16];
@@ -109,12 +200,91 @@ decl_module! {
 				new_dna[i] = combine_dna(kitty1_dna[i], kitty2_dna[i], selector[i]);
 			}
 
-			let new_kitty = Kitty(new_dna);
+			ensure!(!Self::dna_exists(&new_dna), Error::<T>::KittyAlreadyExists);
+			let new_gen = kitty1.gen.max(kitty2.gen).checked_add(1).ok_or(Error::<T>::GenerationOverflow)?;
+			let new_kitty = Kitty { dna: new_dna, gen: new_gen };
 
 			Kitties::<T>::insert(&sender, kitty_id, &new_kitty);
+			Self::insert_owned_kitty(&sender, kitty_id)?;
+			Self::insert_all_kitty(&sender, kitty_id)?;
+			KittyParents::<T>::insert(&sender, kitty_id, (kitty_id_1, kitty_id_2));
+
+			Self::ensure_message_queue_capacity()?;
+			SentMessages::<T>::append(KittyMessage::Created {
+				owner: sender.clone(), kitty_id, dna: new_kitty.dna,
+			});
 
 			Self::deposit_event(RawEvent::KittyBred(sender, kitty_id, new_kitty));
 		}
+
+		/// Transfer a kitty to another account
+		#[weight = 1000]
+		pub fn transfer(origin, to: T::AccountId, kitty_id: u32) {
+			let sender = ensure_signed(origin)?;
+
+			ensure!(to != sender, Error::<T>::CannotTransferToSelf);
+			ensure!(Kitties::<T>::contains_key(&sender, kitty_id), Error::<T>::NotOwner);
+
+			Self::do_transfer(&sender, &to, kitty_id)?;
+
+			Self::deposit_event(RawEvent::KittyTransferred(sender, to, kitty_id));
+		}
+
+		/// List a kitty for sale at `price`, or delist it by passing `None`
+		#[weight = 1000]
+		pub fn set_price(origin, kitty_id: u32, price: Option<BalanceOf<T>>) {
+			let sender = ensure_signed(origin)?;
+
+			ensure!(Kitties::<T>::contains_key(&sender, kitty_id), Error::<T>::NotOwner);
+
+			KittyPrices::<T>::mutate_exists(&sender, kitty_id, |stored_price| *stored_price = price);
+		}
+
+		/// Buy a listed kitty, paying at most `max_price`
+		#[weight = 1000]
+		pub fn buy(origin, owner: T::AccountId, kitty_id: u32, max_price: BalanceOf<T>) {
+			let buyer = ensure_signed(origin)?;
+
+			ensure!(buyer != owner, Error::<T>::CannotTransferToSelf);
+
+			let price = Self::kitty_price(&owner, kitty_id).ok_or(Error::<T>::NotForSale)?;
+			ensure!(price <= max_price, Error::<T>::PriceTooHigh);
+
+			T::Currency::transfer(&buyer, &owner, price, ExistenceRequirement::KeepAlive)?;
+
+			// payment succeeded, so the ownership move below cannot fail on funds alone
+			Self::do_transfer(&owner, &buyer, kitty_id)?;
+
+			Self::deposit_event(RawEvent::KittySold(owner, buyer, kitty_id, price));
+		}
+
+		/// Send a kitty to an external consumer (e.g. another parachain or an off-chain
+		/// worker), removing it from local storage and queuing a message for the relayer
+		#[weight = 1000]
+		pub fn send(origin, dest: T::AccountId, kitty_id: u32) {
+			let sender = ensure_signed(origin)?;
+
+			ensure!(Kitties::<T>::contains_key(&sender, kitty_id), Error::<T>::NotOwner);
+			Self::ensure_message_queue_capacity()?;
+
+			Self::remove_kitty(&sender, kitty_id)?;
+
+			SentMessages::<T>::append(KittyMessage::Transfer { dest: dest.clone(), kitty_id });
+
+			Self::deposit_event(RawEvent::KittyEgressQueued(sender, kitty_id, dest));
+		}
+
+		/// Drain the outbound message queue, handing the relayer a clean slate once it has
+		/// read everything currently queued. Root-only, since the relayer's identity and
+		/// read cursor are managed out-of-band rather than by this pallet.
+		#[weight = 1000]
+		pub fn drain_sent_messages(origin) {
+			ensure_root(origin)?;
+
+			let messages = SentMessages::<T>::take();
+
+			Self::deposit_event(RawEvent::KittyMessagesDrained(messages.len() as u32));
+		}
 	}
 }
 
@@ -140,7 +310,149 @@ impl<T: Config> Module<T> {
 			<pallet_randomness_collective_flip::Module<T> as Randomness<T::Hash>>::random_seed(),
 			&sender,
 			<frame_system::Module<T>>::extrinsic_index(),
+			Self::get_and_increment_nonce(),
 		);
 		payload.using_encoded(blake2_128)
 	}
+
+	fn get_and_increment_nonce() -> u64 {
+		Nonce::mutate(|nonce| {
+			let current = *nonce;
+			*nonce = nonce.wrapping_add(1);
+			current
+		})
+	}
+
+	/// Guards against queuing a message past `MaxSentMessages`, so the queue can't grow
+	/// without bound between relayer drains
+	fn ensure_message_queue_capacity() -> sp_std::result::Result<(), DispatchError> {
+		let queue_len = SentMessages::<T>::decode_len().unwrap_or(0) as u32;
+		ensure!(queue_len < T::MaxSentMessages::get(), Error::<T>::SentMessagesQueueFull);
+		Ok(())
+	}
+
+	/// Whether any kitty in existence, under any owner, already has the given DNA
+	fn dna_exists(dna: &[u8; 16]) -> bool {
+		(0..Self::all_kitties_count()).any(|index| {
+			let (owner, kitty_id) = AllKittiesArray::<T>::get(index);
+			Self::kitties(owner, kitty_id).map_or(false, |kitty| &kitty.dna == dna)
+		})
+	}
+
+	/// Append a freshly created kitty to its owner's gap-free index
+	fn insert_owned_kitty(owner: &T::AccountId, kitty_id: u32) -> sp_std::result::Result<(), DispatchError> {
+		let new_owned_kitty_count = Self::owned_kitties_count(owner)
+			.checked_add(1)
+			.ok_or(Error::<T>::OwnedKittiesCountOverflow)?;
+
+		OwnedKittiesArray::<T>::insert((owner.clone(), new_owned_kitty_count - 1), kitty_id);
+		OwnedKittiesIndex::<T>::insert((owner.clone(), kitty_id), new_owned_kitty_count - 1);
+		OwnedKittiesCount::<T>::insert(owner, new_owned_kitty_count);
+
+		Ok(())
+	}
+
+	/// Move a kitty from `from` to `to`, keeping both owners' indices gap-free by swapping
+	/// the removed slot with the sender's last-indexed kitty before popping it off
+	fn do_transfer(from: &T::AccountId, to: &T::AccountId, kitty_id: u32) -> sp_std::result::Result<(), DispatchError> {
+		let kitty = Self::kitties(from, kitty_id).ok_or(Error::<T>::InvalidKittyId)?;
+
+		let owned_kitty_count_from = Self::owned_kitties_count(from);
+		let owned_kitty_count_to = Self::owned_kitties_count(to);
+
+		let new_owned_kitty_count_to = owned_kitty_count_to
+			.checked_add(1)
+			.ok_or(Error::<T>::OwnedKittiesCountOverflow)?;
+		let new_owned_kitty_count_from = owned_kitty_count_from
+			.checked_sub(1)
+			.ok_or(Error::<T>::OwnedKittiesCountUnderflow)?;
+
+		let kitty_index = OwnedKittiesIndex::<T>::get((from, kitty_id));
+		// swap the kitty being removed with the last one in the sender's list, so the
+		// sender's index stays a gap-free `0..new_owned_kitty_count_from` range
+		if kitty_index != new_owned_kitty_count_from {
+			let last_kitty_id = OwnedKittiesArray::<T>::get((from.clone(), new_owned_kitty_count_from));
+			OwnedKittiesArray::<T>::insert((from.clone(), kitty_index), last_kitty_id);
+			OwnedKittiesIndex::<T>::insert((from.clone(), last_kitty_id), kitty_index);
+		}
+
+		Kitties::<T>::remove(from, kitty_id);
+		OwnedKittiesArray::<T>::remove((from.clone(), new_owned_kitty_count_from));
+		OwnedKittiesIndex::<T>::remove((from.clone(), kitty_id));
+		OwnedKittiesCount::<T>::insert(from, new_owned_kitty_count_from);
+
+		Kitties::<T>::insert(to, kitty_id, kitty);
+		OwnedKittiesArray::<T>::insert((to.clone(), owned_kitty_count_to), kitty_id);
+		OwnedKittiesIndex::<T>::insert((to.clone(), kitty_id), owned_kitty_count_to);
+		OwnedKittiesCount::<T>::insert(to, new_owned_kitty_count_to);
+
+		// the kitty still exists, just under a new owner, so repoint its entry in the
+		// global registry instead of removing and re-appending it
+		let all_kitties_index = AllKittiesIndex::<T>::take((from, kitty_id));
+		AllKittiesArray::<T>::insert(all_kitties_index, (to.clone(), kitty_id));
+		AllKittiesIndex::<T>::insert((to.clone(), kitty_id), all_kitties_index);
+
+		// a sale listing under the old owner is meaningless once ownership moves, so
+		// clear it unconditionally rather than relying on callers (e.g. `buy`) to do so
+		KittyPrices::<T>::remove(from, kitty_id);
+
+		// lineage belongs to the kitty, not the owner, so carry it over too
+		if let Some(parents) = KittyParents::<T>::take(from, kitty_id) {
+			KittyParents::<T>::insert(to, kitty_id, parents);
+		}
+
+		Ok(())
+	}
+
+	/// Append a freshly created kitty to the global registry
+	fn insert_all_kitty(owner: &T::AccountId, kitty_id: u32) -> sp_std::result::Result<(), DispatchError> {
+		let new_all_kitties_count = Self::all_kitties_count()
+			.checked_add(1)
+			.ok_or(Error::<T>::AllKittiesCountOverflow)?;
+
+		AllKittiesArray::<T>::insert(new_all_kitties_count - 1, (owner.clone(), kitty_id));
+		AllKittiesIndex::<T>::insert((owner.clone(), kitty_id), new_all_kitties_count - 1);
+		AllKittiesCount::put(new_all_kitties_count);
+
+		Ok(())
+	}
+
+	/// Remove a kitty from existence entirely, keeping both the owner's and the global
+	/// index gap-free via swap-and-pop
+	fn remove_kitty(owner: &T::AccountId, kitty_id: u32) -> sp_std::result::Result<(), DispatchError> {
+		ensure!(Kitties::<T>::contains_key(owner, kitty_id), Error::<T>::InvalidKittyId);
+
+		let new_owned_kitty_count = Self::owned_kitties_count(owner)
+			.checked_sub(1)
+			.ok_or(Error::<T>::OwnedKittiesCountUnderflow)?;
+
+		let kitty_index = OwnedKittiesIndex::<T>::get((owner, kitty_id));
+		if kitty_index != new_owned_kitty_count {
+			let last_kitty_id = OwnedKittiesArray::<T>::get((owner.clone(), new_owned_kitty_count));
+			OwnedKittiesArray::<T>::insert((owner.clone(), kitty_index), last_kitty_id);
+			OwnedKittiesIndex::<T>::insert((owner.clone(), last_kitty_id), kitty_index);
+		}
+		OwnedKittiesArray::<T>::remove((owner.clone(), new_owned_kitty_count));
+		OwnedKittiesIndex::<T>::remove((owner.clone(), kitty_id));
+		OwnedKittiesCount::<T>::insert(owner, new_owned_kitty_count);
+
+		let new_all_kitties_count = Self::all_kitties_count()
+			.checked_sub(1)
+			.ok_or(Error::<T>::AllKittiesCountUnderflow)?;
+		let all_kitties_index = AllKittiesIndex::<T>::get((owner, kitty_id));
+		if all_kitties_index != new_all_kitties_count {
+			let last_kitty = AllKittiesArray::<T>::get(new_all_kitties_count);
+			AllKittiesArray::<T>::insert(all_kitties_index, last_kitty.clone());
+			AllKittiesIndex::<T>::insert(last_kitty, all_kitties_index);
+		}
+		AllKittiesArray::<T>::remove(new_all_kitties_count);
+		AllKittiesIndex::<T>::remove((owner, kitty_id));
+		AllKittiesCount::put(new_all_kitties_count);
+
+		Kitties::<T>::remove(owner, kitty_id);
+		KittyPrices::<T>::remove(owner, kitty_id);
+		KittyParents::<T>::remove(owner, kitty_id);
+
+		Ok(())
+	}
 }
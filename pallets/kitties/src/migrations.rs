@@ -0,0 +1,27 @@
+//! Storage migrations for the kitties pallet.
+
+use codec::{Encode, Decode};
+use frame_support::{weights::Weight, StorageDoubleMap, StorageValue};
+
+use crate::{Config, Kitty, Kitties, Releases, StorageVersion};
+
+/// The pre-`gen` encoding of [`Kitty`], kept around only so `migrate_to_v2` can decode it.
+#[derive(Encode, Decode)]
+struct KittyV1(pub [u8; 16]);
+
+/// Adds the `gen` field to every stored [`Kitty`], defaulting existing kitties to generation 0.
+pub fn migrate_to_v2<T: Config>() -> Weight {
+	if StorageVersion::get() != Releases::V1 {
+		return 0;
+	}
+
+	let mut migrated: u64 = 0;
+	Kitties::<T>::translate::<KittyV1, _>(|_owner, _kitty_id, old_kitty| {
+		migrated += 1;
+		Some(Kitty { dna: old_kitty.0, gen: 0 })
+	});
+
+	StorageVersion::put(Releases::V2);
+
+	T::DbWeight::get().reads_writes(migrated + 1, migrated + 1)
+}
@@ -0,0 +1,217 @@
+use crate::{mock::*, Error, Kitty, KittyMessage, KittyParents, KittyPrices, Releases, SentMessages};
+use codec::Encode;
+use frame_support::{assert_noop, assert_ok, traits::Currency as _, StorageDoubleMap, StorageValue};
+
+/// Plant a kitty directly into storage, bypassing `create`'s randomness so tests can
+/// pick a specific owner/id/dna without worrying about gender/collision constraints.
+/// `NextKittyId` is moved past `kitty_id`, so a later `create`/`breed` gets a fresh id.
+fn create_kitty(owner: u64, kitty_id: u32, dna: [u8; 16]) {
+	crate::NextKittyId::mutate(|next_id| *next_id = (*next_id).max(kitty_id + 1));
+	let kitty = Kitty { dna, gen: 0 };
+	crate::Kitties::<Test>::insert(owner, kitty_id, &kitty);
+	KittiesModule::insert_owned_kitty(&owner, kitty_id).unwrap();
+	KittiesModule::insert_all_kitty(&owner, kitty_id).unwrap();
+}
+
+#[test]
+fn transfer_rejects_self_transfer() {
+	new_test_ext().execute_with(|| {
+		create_kitty(1, 0, [1u8; 16]);
+
+		assert_noop!(
+			KittiesModule::transfer(Origin::signed(1), 1, 0),
+			Error::<Test>::CannotTransferToSelf
+		);
+		// the owner's accounting must be untouched by the rejected self-transfer
+		assert_eq!(KittiesModule::owned_kitties_count(1), 1);
+	});
+}
+
+#[test]
+fn transfer_moves_ownership_between_distinct_accounts() {
+	new_test_ext().execute_with(|| {
+		create_kitty(1, 0, [2u8; 16]);
+
+		assert_ok!(KittiesModule::transfer(Origin::signed(1), 2, 0));
+
+		assert_eq!(KittiesModule::owned_kitties_count(1), 0);
+		assert_eq!(KittiesModule::owned_kitties_count(2), 1);
+	});
+}
+
+#[test]
+fn buy_rejects_self_purchase() {
+	new_test_ext().execute_with(|| {
+		create_kitty(1, 0, [3u8; 16]);
+		KittyPrices::<Test>::insert(1, 0, 100u64);
+
+		assert_noop!(
+			KittiesModule::buy(Origin::signed(1), 1, 0, 1_000),
+			Error::<Test>::CannotTransferToSelf
+		);
+	});
+}
+
+#[test]
+fn transfer_clears_stale_listing_so_a_later_buy_cannot_charge_for_nothing() {
+	new_test_ext().execute_with(|| {
+		create_kitty(1, 0, [4u8; 16]);
+		KittyPrices::<Test>::insert(1, 0, 100u64);
+
+		assert_ok!(KittiesModule::transfer(Origin::signed(1), 2, 0));
+
+		// the stale listing under the old owner is gone, so a buyer referencing it
+		// fails cleanly instead of paying the old owner for a kitty they no longer hold
+		assert_eq!(KittiesModule::kitty_price(1, 0), None);
+		assert_noop!(
+			KittiesModule::buy(Origin::signed(3), 1, 0, 1_000),
+			Error::<Test>::NotForSale
+		);
+	});
+}
+
+#[test]
+fn transfer_preserves_kitty_parents() {
+	new_test_ext().execute_with(|| {
+		create_kitty(1, 0, [5u8; 16]);
+		KittyParents::<Test>::insert(1, 0, (10u32, 11u32));
+
+		assert_ok!(KittiesModule::transfer(Origin::signed(1), 2, 0));
+
+		assert_eq!(KittiesModule::kitty_parents(2, 0), Some((10, 11)));
+		assert_eq!(KittiesModule::kitty_parents(1, 0), None);
+	});
+}
+
+#[test]
+fn send_rejects_once_the_queue_is_full() {
+	// mock's MaxSentMessages is 2
+	new_test_ext().execute_with(|| {
+		create_kitty(1, 0, [6u8; 16]);
+		create_kitty(1, 1, [7u8; 16]);
+		create_kitty(1, 2, [8u8; 16]);
+
+		assert_ok!(KittiesModule::send(Origin::signed(1), 2, 0));
+		assert_ok!(KittiesModule::send(Origin::signed(1), 2, 1));
+		assert_noop!(
+			KittiesModule::send(Origin::signed(1), 2, 2),
+			Error::<Test>::SentMessagesQueueFull
+		);
+		assert_eq!(SentMessages::<Test>::get().len(), 2);
+	});
+}
+
+#[test]
+fn drain_sent_messages_requires_root_and_empties_the_queue() {
+	new_test_ext().execute_with(|| {
+		create_kitty(1, 0, [9u8; 16]);
+		assert_ok!(KittiesModule::send(Origin::signed(1), 2, 0));
+
+		assert_noop!(
+			KittiesModule::drain_sent_messages(Origin::signed(1)),
+			sp_runtime::DispatchError::BadOrigin
+		);
+
+		assert_ok!(KittiesModule::drain_sent_messages(frame_system::RawOrigin::Root.into()));
+		assert_eq!(SentMessages::<Test>::get().len(), 0);
+	});
+}
+
+#[test]
+fn buy_moves_currency_and_ownership() {
+	new_test_ext().execute_with(|| {
+		create_kitty(1, 0, [20u8; 16]);
+		KittyPrices::<Test>::insert(1, 0, 100u64);
+
+		assert_ok!(KittiesModule::buy(Origin::signed(2), 1, 0, 1_000));
+
+		assert_eq!(Balances::free_balance(1), 1_100);
+		assert_eq!(Balances::free_balance(2), 900);
+		assert_eq!(KittiesModule::kitty_price(1, 0), None);
+		assert_eq!(KittiesModule::owned_kitties_count(1), 0);
+		assert_eq!(KittiesModule::owned_kitties_count(2), 1);
+	});
+}
+
+#[test]
+fn dna_exists_detects_collisions_across_different_owners() {
+	new_test_ext().execute_with(|| {
+		create_kitty(1, 0, [42u8; 16]);
+
+		// a different owner's kitty with the same DNA must still count as a collision
+		assert!(KittiesModule::dna_exists(&[42u8; 16]));
+		assert!(!KittiesModule::dna_exists(&[43u8; 16]));
+	});
+}
+
+#[test]
+fn create_stores_a_gen_zero_kitty_owned_by_sender() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(KittiesModule::create(Origin::signed(1)));
+
+		assert_eq!(KittiesModule::owned_kitties_count(1), 1);
+		let kitty_id = KittiesModule::kitty_of_owner_by_index((1u64, 0u32));
+		let kitty = KittiesModule::kitties(1, kitty_id).unwrap();
+		assert_eq!(kitty.gen, 0);
+	});
+}
+
+#[test]
+fn create_queues_a_created_message_for_relayers() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(KittiesModule::create(Origin::signed(1)));
+
+		let kitty_id = KittiesModule::kitty_of_owner_by_index((1u64, 0u32));
+		let kitty = KittiesModule::kitties(1, kitty_id).unwrap();
+		assert_eq!(
+			SentMessages::<Test>::get(),
+			vec![KittyMessage::Created { owner: 1, kitty_id, dna: kitty.dna }]
+		);
+	});
+}
+
+#[test]
+fn breed_requires_different_genders() {
+	new_test_ext().execute_with(|| {
+		create_kitty(1, 0, [0u8; 16]); // even first byte -> male
+		create_kitty(1, 1, [2u8; 16]); // even first byte -> male
+
+		assert_noop!(
+			KittiesModule::breed(Origin::signed(1), 0, 1),
+			Error::<Test>::SameGender
+		);
+	});
+}
+
+#[test]
+fn breed_sets_generation_and_records_parents() {
+	new_test_ext().execute_with(|| {
+		create_kitty(1, 0, [0u8; 16]); // male
+		create_kitty(1, 1, [1u8; 16]); // odd first byte -> female
+
+		assert_ok!(KittiesModule::breed(Origin::signed(1), 0, 1));
+
+		let child_id = KittiesModule::kitty_of_owner_by_index((1u64, 2u32));
+		let child = KittiesModule::kitties(1, child_id).unwrap();
+		assert_eq!(child.gen, 1);
+		assert_eq!(KittiesModule::kitty_parents(1, child_id), Some((0, 1)));
+	});
+}
+
+#[test]
+fn migrate_to_v2_backfills_gen_zero_and_bumps_storage_version() {
+	new_test_ext().execute_with(|| {
+		// write a V1-encoded kitty (a bare `[u8; 16]`, which is how the pre-`gen`
+		// newtype wrapper encoded) directly under the double map's storage key
+		let dna = [7u8; 16];
+		let key = crate::Kitties::<Test>::hashed_key_for(1u64, 0u32);
+		frame_support::storage::unhashed::put_raw(&key, &dna.encode());
+
+		crate::migrations::migrate_to_v2::<Test>();
+
+		let migrated = KittiesModule::kitties(1, 0).unwrap();
+		assert_eq!(migrated.dna, dna);
+		assert_eq!(migrated.gen, 0);
+		assert_eq!(KittiesModule::storage_version(), Releases::V2);
+	});
+}